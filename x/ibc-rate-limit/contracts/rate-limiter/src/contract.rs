@@ -1,11 +1,46 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Timestamp};
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Storage, Timestamp,
+};
 use cw2::set_contract_version;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg};
-use crate::state::{Flow, FlowType, Quota, FLOW, GOVMODULE, IBCMODULE, QUOTAS};
+use crate::msg::{
+    ExecuteMsg, FlowResponse, InstantiateMsg, QueryMsg, QuotaFlowResponse, QuotaMsg, QuotasResponse,
+};
+use crate::state::{
+    Flow, FlowMode, FlowType, Quota, DEFAULT_DENOM, FLOW, GOVMODULE, IBCMODULE, QUOTAS,
+};
+
+fn enforce_gov_module(deps: &DepsMut, sender: &Addr) -> Result<(), ContractError> {
+    let gov_module = GOVMODULE.load(deps.storage)?;
+    if sender != gov_module {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+/// Looks up the quotas scoped to `denom` on `channel_id`, falling back to
+/// `DEFAULT_DENOM`'s quotas if `denom` has none configured of its own.
+/// Returns the denom whose quotas were actually used, so callers key `FLOW`
+/// consistently with where the quota came from.
+fn resolve_quotas(
+    storage: &dyn Storage,
+    channel_id: &str,
+    denom: &str,
+) -> StdResult<(String, Vec<Quota>)> {
+    if let Some(quotas) = QUOTAS.may_load(storage, (channel_id.to_string(), denom.to_string()))? {
+        if !quotas.is_empty() {
+            return Ok((denom.to_string(), quotas));
+        }
+    }
+    let quotas = QUOTAS
+        .may_load(storage, (channel_id.to_string(), DEFAULT_DENOM.to_string()))?
+        .unwrap_or_default();
+    Ok((DEFAULT_DENOM.to_string(), quotas))
+}
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:rate-limiter";
@@ -22,13 +57,14 @@ pub fn instantiate(
     IBCMODULE.save(deps.storage, &msg.ibc_module)?;
     GOVMODULE.save(deps.storage, &msg.gov_module)?;
 
-    for (channel, quotas) in msg.channel_quotas {
-        QUOTAS.save(deps.storage, channel.clone(), &vec![quotas.into()])?;
+    for (channel, denom, quota) in msg.channel_quotas {
+        let quota: Quota = quota.into();
         FLOW.save(
             deps.storage,
-            channel,
-            &Flow::new(0_u128, 0_u128, env.block.time),
+            (channel.clone(), denom.clone(), quota.name().to_string()),
+            &Flow::new(0_u128, 0_u128, env.block.time, quota.duration_secs()),
         )?;
+        QUOTAS.save(deps.storage, (channel, denom), &vec![quota])?;
     }
 
     Ok(Response::new()
@@ -47,12 +83,14 @@ pub fn execute(
     match msg {
         ExecuteMsg::SendPacket {
             channel_id,
+            denom,
             channel_value,
             funds,
         } => try_transfer(
             deps,
             info.sender,
             channel_id,
+            denom,
             channel_value,
             funds,
             FlowType::Out,
@@ -60,22 +98,161 @@ pub fn execute(
         ),
         ExecuteMsg::RecvPacket {
             channel_id,
+            denom,
             channel_value,
             funds,
         } => try_transfer(
             deps,
             info.sender,
             channel_id,
+            denom,
             channel_value,
             funds,
             FlowType::In,
             env.block.time,
         ),
-        ExecuteMsg::AddChannel {} => todo!(),
-        ExecuteMsg::RemoveChannel {} => todo!(),
+        ExecuteMsg::AddChannel {
+            channel_id,
+            denom,
+            quotas,
+        } => try_add_channel(deps, env, info.sender, channel_id, denom, quotas),
+        ExecuteMsg::RemoveChannel { channel_id, denom } => {
+            try_remove_channel(deps, info.sender, channel_id, denom)
+        }
+        ExecuteMsg::SetQuotas {
+            channel_id,
+            denom,
+            quotas,
+        } => try_set_quotas(deps, env, info.sender, channel_id, denom, quotas),
+    }
+}
+
+/// Removes `FLOW` rows for quota names that existed in `old_quotas` but are
+/// absent from `new_quotas`, so replacing a channel/denom's quotas with
+/// differently-named ones doesn't leave orphaned flow entries behind.
+fn remove_stale_flows(
+    storage: &mut dyn Storage,
+    channel_id: &str,
+    denom: &str,
+    old_quotas: &[Quota],
+    new_quotas: &[Quota],
+) {
+    let new_names: std::collections::HashSet<&str> =
+        new_quotas.iter().map(|quota| quota.name()).collect();
+    for quota in old_quotas {
+        if !new_names.contains(quota.name()) {
+            FLOW.remove(
+                storage,
+                (
+                    channel_id.to_string(),
+                    denom.to_string(),
+                    quota.name().to_string(),
+                ),
+            );
+        }
     }
 }
 
+pub fn try_add_channel(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    channel_id: String,
+    denom: String,
+    quotas: Vec<QuotaMsg>,
+) -> Result<Response, ContractError> {
+    enforce_gov_module(&deps, &sender)?;
+
+    let quotas: Vec<Quota> = quotas.into_iter().map(Quota::from).collect();
+    let key = (channel_id.clone(), denom.clone());
+    let old_quotas = QUOTAS
+        .may_load(deps.storage, key.clone())?
+        .unwrap_or_default();
+    remove_stale_flows(deps.storage, &channel_id, &denom, &old_quotas, &quotas);
+
+    for quota in &quotas {
+        FLOW.save(
+            deps.storage,
+            (channel_id.clone(), denom.clone(), quota.name().to_string()),
+            &Flow::new(0_u128, 0_u128, env.block.time, quota.duration_secs()),
+        )?;
+    }
+    QUOTAS.save(deps.storage, key, &quotas)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "add_channel")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
+pub fn try_remove_channel(
+    deps: DepsMut,
+    sender: Addr,
+    channel_id: String,
+    denom: String,
+) -> Result<Response, ContractError> {
+    enforce_gov_module(&deps, &sender)?;
+
+    let key = (channel_id.clone(), denom.clone());
+    let quotas = QUOTAS
+        .may_load(deps.storage, key.clone())?
+        .unwrap_or_default();
+    for quota in &quotas {
+        FLOW.remove(
+            deps.storage,
+            (channel_id.clone(), denom.clone(), quota.name().to_string()),
+        );
+    }
+    QUOTAS.remove(deps.storage, key);
+
+    Ok(Response::new()
+        .add_attribute("method", "remove_channel")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom))
+}
+
+pub fn try_set_quotas(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    channel_id: String,
+    denom: String,
+    quotas: Vec<QuotaMsg>,
+) -> Result<Response, ContractError> {
+    enforce_gov_module(&deps, &sender)?;
+
+    // The channel's accumulated flow is intentionally left untouched here:
+    // governance can raise or lower a quota's percentages without resetting
+    // the window the channel is currently in. Only brand-new quota names get
+    // a fresh flow initialized, and names dropped from the new set have
+    // their flow removed so they don't linger as orphaned rows.
+    let quotas: Vec<Quota> = quotas.into_iter().map(Quota::from).collect();
+    let key = (channel_id.clone(), denom.clone());
+    let old_quotas = QUOTAS
+        .may_load(deps.storage, key.clone())?
+        .unwrap_or_default();
+    remove_stale_flows(deps.storage, &channel_id, &denom, &old_quotas, &quotas);
+
+    for quota in &quotas {
+        let flow_key = (channel_id.clone(), denom.clone(), quota.name().to_string());
+        if FLOW.may_load(deps.storage, flow_key.clone())?.is_none() {
+            FLOW.save(
+                deps.storage,
+                flow_key,
+                &Flow::new(0_u128, 0_u128, env.block.time, quota.duration_secs()),
+            )?;
+        }
+    }
+    QUOTAS.save(deps.storage, key, &quotas)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "set_quotas")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom)
+        .add_attribute("quotas", quotas.len().to_string()))
+}
+
 fn check_quota(
     quota: &Quota,
     flow: &mut Flow,
@@ -85,26 +262,43 @@ fn check_quota(
     funds: u128,
     now: Timestamp,
 ) -> Result<(u128, u128, Timestamp), ContractError> {
-    let max = quota.capacity_at(&channel_value, &direction);
-    if flow.is_expired(now) {
-        flow.expire(now)
-    }
-    flow.add_flow(direction, funds);
+    let max = quota.capacity_at(&channel_value, &direction)?;
+    let balance = match quota.mode() {
+        FlowMode::Fixed => {
+            if flow.is_expired(now) {
+                flow.expire(now, quota.duration_secs())
+            }
+            flow.add_flow(direction, funds);
+            flow.balance()
+        }
+        FlowMode::Rolling { granularity } => {
+            flow.prune_buckets(now, quota.duration_secs(), *granularity);
+            flow.add_to_bucket(now, *granularity, direction, funds);
+            flow.rolling_balance()
+        }
+        FlowMode::Weighted => {
+            flow.maybe_roll_period(now, quota.duration_secs());
+            flow.add_flow(direction, funds);
+            flow.weighted_balance(now, quota.duration_secs())?
+        }
+    };
+    let reset = flow.reset_at(quota.mode(), now, quota.duration_secs());
 
-    let balance = flow.balance();
     if balance > max {
         return Err(ContractError::RateLimitExceded {
             channel: channel_id.to_string(),
-            reset: flow.period_end,
+            reset,
         });
     }
-    return Ok((balance, max, flow.period_end));
+    return Ok((balance, max, reset));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn try_transfer(
     deps: DepsMut,
     sender: Addr,
     channel_id: String,
+    denom: String,
     channel_value: u128,
     funds: u128,
     direction: FlowType,
@@ -115,42 +309,47 @@ pub fn try_transfer(
     if sender != ibc_module {
         return Err(ContractError::Unauthorized {});
     }
-    let quotas = QUOTAS.load(deps.storage, channel_id.clone())?;
-    if quotas.len() == 0 {
-        // No Quota configured for the current channel. Allowing all messages.
+    // A denom without a rule of its own is throttled by the channel's
+    // DEFAULT_DENOM quotas instead, sharing their flow with every other denom
+    // that falls back to them. Denoms with their own rule track separate flow.
+    let (denom, quotas) = resolve_quotas(deps.storage, &channel_id, &denom)?;
+    if quotas.is_empty() {
+        // No Quota configured for this channel/denom. Allowing all messages.
         return Ok(Response::new()
             .add_attribute("method", "try_transfer")
             .add_attribute("channel_id", channel_id)
+            .add_attribute("denom", denom)
             .add_attribute("quota", "none"));
     }
 
-    let mut flow = FLOW.load(deps.storage, channel_id.clone())?;
-
-    let quotas: Result<Vec<(u128, u128, Timestamp)>, _> = quotas
-        .iter()
-        .map(|quota| {
-            check_quota(
-                &quota,
-                &mut flow,
-                direction.clone(),
-                &channel_id,
-                channel_value,
-                funds,
-                now,
-            )
-        })
-        .collect();
-    let quotas = quotas?;
+    // Each quota tracks its own flow independently, so a tight daily quota
+    // and a looser weekly one on the same channel are both enforced without
+    // interfering with each other.
+    let mut results = Vec::with_capacity(quotas.len());
+    for quota in &quotas {
+        let key = (channel_id.clone(), denom.clone(), quota.name().to_string());
+        let mut flow = FLOW
+            .may_load(deps.storage, key.clone())?
+            .unwrap_or_else(|| Flow::new(0_u128, 0_u128, now, quota.duration_secs()));
 
-    FLOW.update(
-        deps.storage,
-        channel_id.clone(),
-        |_| -> Result<_, ContractError> { Ok(flow) },
-    )?;
+        let result = check_quota(
+            quota,
+            &mut flow,
+            direction.clone(),
+            &channel_id,
+            channel_value,
+            funds,
+            now,
+        )?;
+        FLOW.save(deps.storage, key, &flow)?;
+        results.push(result);
+    }
+    let quotas = results;
 
     let response = Response::new()
         .add_attribute("method", "try_transfer")
-        .add_attribute("channel_id", channel_id);
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("denom", denom);
 
     // Adding the attributes from each quota to the response
     quotas.iter().fold(Ok(response), |acc, quota| {
@@ -167,14 +366,86 @@ pub fn try_transfer(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(_deps: Deps, _env: Env, _msg: ExecuteMsg) -> StdResult<Binary> {
-    todo!()
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetQuotas { channel_id, denom } => {
+            to_binary(&query_quotas(deps, channel_id, denom)?)
+        }
+        QueryMsg::GetFlow {
+            channel_id,
+            denom,
+            channel_value,
+        } => to_binary(&query_flow(deps, env, channel_id, denom, channel_value)?),
+    }
+}
+
+fn query_quotas(deps: Deps, channel_id: String, denom: String) -> StdResult<QuotasResponse> {
+    let (_, quotas) = resolve_quotas(deps.storage, &channel_id, &denom)?;
+    Ok(QuotasResponse { quotas })
+}
+
+fn query_flow(
+    deps: Deps,
+    env: Env,
+    channel_id: String,
+    denom: String,
+    channel_value: u128,
+) -> StdResult<FlowResponse> {
+    let (denom, quotas) = resolve_quotas(deps.storage, &channel_id, &denom)?;
+    let now = env.block.time;
+
+    // The channel is throttled by whichever quota has the least headroom;
+    // a channel without any quota configured has unlimited headroom.
+    let mut available_send = u128::MAX;
+    let mut available_recv = u128::MAX;
+    // Each quota tracks independent flow (see `FLOW`'s doc comment), so its
+    // flow is reported per-quota rather than summed: a tight daily quota and
+    // a looser weekly one reflect the same underlying transfers, and adding
+    // them together would double count.
+    let mut quota_flows = Vec::with_capacity(quotas.len());
+
+    for quota in &quotas {
+        let key = (channel_id.clone(), denom.clone(), quota.name().to_string());
+        let flow = FLOW
+            .may_load(deps.storage, key)?
+            .unwrap_or_else(|| Flow::new(0_u128, 0_u128, now, quota.duration_secs()));
+        // Mode-aware, like `available_send`/`available_recv` below: raw
+        // `flow.inflow`/`flow.outflow` are never populated for `Rolling`
+        // (its flow lives in `buckets`) and are un-weighted for `Weighted`.
+        let (inflow, outflow) = flow
+            .effective_flow_for_mode(quota.mode(), now, quota.duration_secs())
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let balance = inflow.abs_diff(outflow);
+
+        let max_send = quota
+            .capacity_at(&channel_value, &FlowType::Out)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let max_recv = quota
+            .capacity_at(&channel_value, &FlowType::In)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        available_send = available_send.min(max_send.saturating_sub(balance));
+        available_recv = available_recv.min(max_recv.saturating_sub(balance));
+
+        quota_flows.push(QuotaFlowResponse {
+            quota_name: quota.name().to_string(),
+            inflow,
+            outflow,
+            balance,
+            period_end: flow.reset_at(quota.mode(), now, quota.duration_secs()),
+        });
+    }
+
+    Ok(FlowResponse {
+        quotas: quota_flows,
+        available_send,
+        available_recv,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use crate::msg::QuotaMsg;
-    use crate::state::RESET_TIME_WEEKLY;
+    use crate::state::{DEFAULT_DENOM, RESET_TIME_WEEKLY};
 
     use super::*;
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
@@ -209,13 +480,14 @@ mod tests {
         let msg = InstantiateMsg {
             gov_module: Addr::unchecked(GOV_ADDR),
             ibc_module: Addr::unchecked(IBC_ADDR),
-            channel_quotas: vec![("channel".to_string(), quota)],
+            channel_quotas: vec![("channel".to_string(), DEFAULT_DENOM.to_string(), quota)],
         };
         let info = mock_info(IBC_ADDR, &vec![]);
         instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
 
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
@@ -227,6 +499,7 @@ mod tests {
 
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
@@ -242,24 +515,26 @@ mod tests {
         let msg = InstantiateMsg {
             gov_module: Addr::unchecked(GOV_ADDR),
             ibc_module: Addr::unchecked(IBC_ADDR),
-            channel_quotas: vec![("channel".to_string(), quota)],
+            channel_quotas: vec![("channel".to_string(), DEFAULT_DENOM.to_string(), quota)],
         };
         let info = mock_info(GOV_ADDR, &vec![]);
         let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
 
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
         let info = mock_info(IBC_ADDR, &vec![]);
         let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "300");
 
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
@@ -275,7 +550,7 @@ mod tests {
         let msg = InstantiateMsg {
             gov_module: Addr::unchecked(GOV_ADDR),
             ibc_module: Addr::unchecked(IBC_ADDR),
-            channel_quotas: vec![("channel".to_string(), quota)],
+            channel_quotas: vec![("channel".to_string(), DEFAULT_DENOM.to_string(), quota)],
         };
         let info = mock_info(GOV_ADDR, &vec![]);
         let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
@@ -283,22 +558,24 @@ mod tests {
         let info = mock_info(IBC_ADDR, &vec![]);
         let send_msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
         let recv_msg = ExecuteMsg::RecvPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 300,
         };
 
         let res = execute(deps.as_mut(), mock_env(), info.clone(), send_msg.clone()).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "300");
 
         let res = execute(deps.as_mut(), mock_env(), info.clone(), recv_msg.clone()).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "0");
 
@@ -306,7 +583,7 @@ mod tests {
         // allowance through the channel (900 > 3000*.1), the current "balance"
         // of inflow vs outflow is still lower than the channel's capacity/quota
         let res = execute(deps.as_mut(), mock_env(), info.clone(), recv_msg.clone()).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "300");
 
@@ -324,7 +601,7 @@ mod tests {
         let msg = InstantiateMsg {
             gov_module: Addr::unchecked(GOV_ADDR),
             ibc_module: Addr::unchecked(IBC_ADDR),
-            channel_quotas: vec![("channel".to_string(), quota)],
+            channel_quotas: vec![("channel".to_string(), DEFAULT_DENOM.to_string(), quota)],
         };
         let info = mock_info(GOV_ADDR, &vec![]);
         let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
@@ -332,31 +609,34 @@ mod tests {
         // Sending 2%
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 60,
         };
         let info = mock_info(IBC_ADDR, &vec![]);
         let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "60");
 
         // Sending 1% more. Allowed, as sending has a 10% allowance
         let msg = ExecuteMsg::SendPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 30,
         };
 
         let info = mock_info(IBC_ADDR, &vec![]);
         let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let Attribute { key, value } = &res.attributes[2];
+        let Attribute { key, value } = &res.attributes[3];
         assert_eq!(key, "used");
         assert_eq!(value, "90");
 
         // Receiving 1% should fail. 3% already executed through the channel
         let recv_msg = ExecuteMsg::RecvPacket {
             channel_id: "channel".to_string(),
+            denom: "uosmo".to_string(),
             channel_value: 3_000,
             funds: 30,
         };
@@ -364,4 +644,557 @@ mod tests {
         let err = execute(deps.as_mut(), mock_env(), info.clone(), recv_msg.clone()).unwrap_err();
         assert!(matches!(err, ContractError::RateLimitExceded { .. }));
     }
+
+    #[test]
+    fn query_quotas_and_flow() {
+        let mut deps = mock_dependencies();
+
+        let quota = QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 10);
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![("channel".to_string(), DEFAULT_DENOM.to_string(), quota)],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let quotas: QuotasResponse = cosmwasm_std::from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetQuotas {
+                    channel_id: "channel".to_string(),
+                    denom: "uosmo".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(1, quotas.quotas.len());
+
+        let info = mock_info(IBC_ADDR, &vec![]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "uosmo".to_string(),
+                channel_value: 3_000,
+                funds: 300,
+            },
+        )
+        .unwrap();
+
+        let flow: FlowResponse = cosmwasm_std::from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetFlow {
+                    channel_id: "channel".to_string(),
+                    denom: "uosmo".to_string(),
+                    channel_value: 3_000,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(1, flow.quotas.len());
+        assert_eq!(300, flow.quotas[0].outflow);
+        assert_eq!(300, flow.quotas[0].balance);
+        assert_eq!(0, flow.available_send);
+        assert_eq!(0, flow.available_recv);
+    }
+
+    #[test]
+    fn query_flow_reports_balance_for_rolling_quota() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![QuotaMsg::new_with_mode(
+                    "Hourly",
+                    60 * 60,
+                    10,
+                    10,
+                    FlowMode::Rolling {
+                        granularity: 60 * 60,
+                    },
+                )],
+            },
+        )
+        .unwrap();
+
+        let info = mock_info(IBC_ADDR, &vec![]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "uosmo".to_string(),
+                channel_value: 3_000,
+                funds: 300,
+            },
+        )
+        .unwrap();
+
+        // A rolling quota's flow lives in buckets, not the raw
+        // `inflow`/`outflow` fields. `GetFlow` must report it anyway, so a
+        // caller can actually predict whether the next transfer throttles.
+        let flow: FlowResponse = cosmwasm_std::from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetFlow {
+                    channel_id: "channel".to_string(),
+                    denom: "uosmo".to_string(),
+                    channel_value: 3_000,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(1, flow.quotas.len());
+        assert_eq!(300, flow.quotas[0].outflow);
+        assert_eq!(300, flow.quotas[0].balance);
+        assert_eq!(0, flow.available_send);
+    }
+
+    #[test]
+    fn mixed_period_quotas_track_independent_flow() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        // A tight daily quota alongside a looser weekly one on the same channel.
+        let daily = QuotaMsg::new("Daily", 60 * 60 * 24, 1, 1);
+        let weekly = QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 10);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![daily, weekly],
+            },
+        )
+        .unwrap();
+
+        let info = mock_info(IBC_ADDR, &vec![]);
+
+        // 1% of 3_000 exhausts the daily quota but barely dents the weekly one.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "uosmo".to_string(),
+                channel_value: 3_000,
+                funds: 30,
+            },
+        )
+        .unwrap();
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "uosmo".to_string(),
+                channel_value: 3_000,
+                funds: 1,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+        let daily_flow = FLOW
+            .load(
+                deps.as_ref().storage,
+                (
+                    "channel".to_string(),
+                    DEFAULT_DENOM.to_string(),
+                    "Daily".to_string(),
+                ),
+            )
+            .unwrap();
+        let weekly_flow = FLOW
+            .load(
+                deps.as_ref().storage,
+                (
+                    "channel".to_string(),
+                    DEFAULT_DENOM.to_string(),
+                    "Weekly".to_string(),
+                ),
+            )
+            .unwrap();
+        assert_eq!(30, daily_flow.outflow);
+        assert_eq!(30, weekly_flow.outflow);
+    }
+
+    #[test]
+    fn replacing_quotas_removes_orphaned_flow_for_dropped_names() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let flow_key = |name: &str| {
+            (
+                "channel".to_string(),
+                DEFAULT_DENOM.to_string(),
+                name.to_string(),
+            )
+        };
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![QuotaMsg::new("Daily", 60 * 60 * 24, 10, 10)],
+            },
+        )
+        .unwrap();
+        assert!(FLOW
+            .may_load(deps.as_ref().storage, flow_key("Daily"))
+            .unwrap()
+            .is_some());
+
+        // Re-adding the channel with a differently-named quota must not
+        // leave the old "Daily" flow behind as an orphaned row.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 10)],
+            },
+        )
+        .unwrap();
+        assert!(FLOW
+            .may_load(deps.as_ref().storage, flow_key("Daily"))
+            .unwrap()
+            .is_none());
+        assert!(FLOW
+            .may_load(deps.as_ref().storage, flow_key("Weekly"))
+            .unwrap()
+            .is_some());
+
+        // Same goes for `SetQuotas`.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SetQuotas {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![QuotaMsg::new("Monthly", 60 * 60 * 24 * 30, 10, 10)],
+            },
+        )
+        .unwrap();
+        assert!(FLOW
+            .may_load(deps.as_ref().storage, flow_key("Weekly"))
+            .unwrap()
+            .is_none());
+        assert!(FLOW
+            .may_load(deps.as_ref().storage, flow_key("Monthly"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn query_flow_does_not_double_count_across_quotas() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let daily = QuotaMsg::new("Daily", 60 * 60 * 24, 10, 10);
+        let weekly = QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 10);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![daily, weekly],
+            },
+        )
+        .unwrap();
+
+        let info = mock_info(IBC_ADDR, &vec![]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "uosmo".to_string(),
+                channel_value: 3_000,
+                funds: 30,
+            },
+        )
+        .unwrap();
+
+        // Each quota reflects the same 30 sent; summing them would wrongly
+        // report 60.
+        let flow: FlowResponse = cosmwasm_std::from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetFlow {
+                    channel_id: "channel".to_string(),
+                    denom: "uosmo".to_string(),
+                    channel_value: 3_000,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(2, flow.quotas.len());
+        for quota_flow in &flow.quotas {
+            assert_eq!(30, quota_flow.outflow);
+            assert_eq!(30, quota_flow.balance);
+        }
+    }
+
+    #[test]
+    fn denom_scoped_quota_does_not_share_allowance_with_other_denoms() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            gov_module: Addr::unchecked(GOV_ADDR),
+            ibc_module: Addr::unchecked(IBC_ADDR),
+            channel_quotas: vec![],
+        };
+        let info = mock_info(GOV_ADDR, &vec![]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        // A tight quota scoped only to "rare", plus a looser default quota
+        // that covers every other denom on the channel.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: "rare".to_string(),
+                quotas: vec![QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 1, 1)],
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AddChannel {
+                channel_id: "channel".to_string(),
+                denom: DEFAULT_DENOM.to_string(),
+                quotas: vec![QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 10)],
+            },
+        )
+        .unwrap();
+
+        let info = mock_info(IBC_ADDR, &vec![]);
+
+        // 1% of "rare" exhausts its own tight quota...
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "rare".to_string(),
+                channel_value: 3_000,
+                funds: 30,
+            },
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "rare".to_string(),
+                channel_value: 3_000,
+                funds: 1,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::RateLimitExceded { .. }));
+
+        // ...but an unrelated denom, falling back to the default quota, is
+        // unaffected.
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::SendPacket {
+                channel_id: "channel".to_string(),
+                denom: "common".to_string(),
+                channel_value: 3_000,
+                funds: 300,
+            },
+        )
+        .unwrap();
+        let Attribute { key, value } = &res.attributes[3];
+        assert_eq!(key, "used");
+        assert_eq!(value, "300");
+    }
+
+    #[test]
+    fn capacity_at_does_not_overflow_for_high_channel_values() {
+        let quota = Quota::from(QuotaMsg::new("Weekly", RESET_TIME_WEEKLY, 10, 50));
+
+        // `total_value * max_percentage` overflows u128 well before
+        // `total_value` itself does; the 256-bit intermediate must still
+        // land on the correct, in-range answer.
+        let capacity = quota.capacity_at(&u128::MAX, &FlowType::Out).unwrap();
+        assert_eq!(u128::MAX / 10, capacity);
+
+        let capacity = quota.capacity_at(&u128::MAX, &FlowType::In).unwrap();
+        assert_eq!(u128::MAX / 2, capacity);
+    }
+
+    #[test]
+    fn rolling_window_prunes_old_buckets() {
+        let quota = Quota::from(QuotaMsg::new_with_mode(
+            "Hourly",
+            60 * 60 * 3,
+            10,
+            10,
+            FlowMode::Rolling {
+                granularity: 60 * 60,
+            },
+        ));
+        let mut flow = Flow::new(
+            0_u128,
+            0_u128,
+            Timestamp::from_seconds(0),
+            quota.duration_secs(),
+        );
+
+        // 300 sent in the first bucket (t=0h)
+        check_quota(
+            &quota,
+            &mut flow,
+            FlowType::Out,
+            "channel",
+            3_000,
+            300,
+            Timestamp::from_seconds(0),
+        )
+        .unwrap();
+        assert_eq!(300, flow.rolling_balance());
+
+        // Sliding forward by 4 hours drops the t=0h bucket, now 4 buckets
+        // behind the current one (3 behind would still be kept)
+        flow.prune_buckets(
+            Timestamp::from_seconds(60 * 60 * 4),
+            quota.duration_secs(),
+            60 * 60,
+        );
+        assert_eq!(0, flow.rolling_balance());
+    }
+
+    #[test]
+    fn weighted_mode_decays_previous_period() {
+        let quota = Quota::from(QuotaMsg::new_with_mode(
+            "Weighted-Weekly",
+            RESET_TIME_WEEKLY,
+            10,
+            10,
+            FlowMode::Weighted,
+        ));
+        let mut flow = Flow::new(
+            0_u128,
+            0_u128,
+            Timestamp::from_seconds(0),
+            quota.duration_secs(),
+        );
+
+        check_quota(
+            &quota,
+            &mut flow,
+            FlowType::Out,
+            "channel",
+            3_000,
+            300,
+            Timestamp::from_seconds(0),
+        )
+        .unwrap();
+
+        // Roll into the next period: the 300 sent becomes `prev_outflow`.
+        let next_period_start = Timestamp::from_seconds(RESET_TIME_WEEKLY + 1);
+        flow.maybe_roll_period(next_period_start, quota.duration_secs());
+
+        // Halfway through that new period, half of the previous period's
+        // flow should still be weighing on the balance.
+        let halfway = next_period_start.plus_seconds(RESET_TIME_WEEKLY / 2);
+        let balance = flow
+            .weighted_balance(halfway, quota.duration_secs())
+            .unwrap();
+        assert_eq!(150, balance);
+    }
+
+    #[test]
+    fn weighted_balance_does_not_overflow_for_high_prev_flow() {
+        // `prev_outflow` near `u128::MAX` (a high-TVL, 100%-quota channel)
+        // times a near-max `remaining` must still land on the correct,
+        // in-range answer instead of panicking/wrapping on the raw multiply.
+        let flow = Flow {
+            inflow: 0,
+            outflow: 0,
+            period_start: Timestamp::from_seconds(0),
+            period_end: Timestamp::from_seconds(RESET_TIME_WEEKLY),
+            prev_inflow: 0,
+            prev_outflow: u128::MAX,
+            buckets: vec![],
+        };
+
+        let balance = flow
+            .weighted_balance(Timestamp::from_seconds(0), RESET_TIME_WEEKLY)
+            .unwrap();
+        assert_eq!(u128::MAX, balance);
+    }
 }
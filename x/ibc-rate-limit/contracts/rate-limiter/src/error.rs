@@ -0,0 +1,17 @@
+use cosmwasm_std::{StdError, Timestamp};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Rate limit exceeded for channel {channel}. Try again after {reset}")]
+    RateLimitExceded { channel: String, reset: Timestamp },
+
+    #[error("Overflow computing quota capacity")]
+    Overflow {},
+}
@@ -0,0 +1,137 @@
+use cosmwasm_std::{Addr, Timestamp};
+use cw_utils::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{FlowMode, Quota};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuotaMsg {
+    pub name: String,
+    pub duration: Duration,
+    /// (max_percentage_send, max_percentage_recv)
+    pub send_recv: (u32, u32),
+    /// How the quota's flow is aged out over time. Defaults to `Fixed` for
+    /// backwards compatibility with quotas configured before this field existed.
+    #[serde(default)]
+    pub mode: FlowMode,
+}
+
+impl QuotaMsg {
+    pub fn new(
+        name: &str,
+        duration: u64,
+        max_percentage_send: u32,
+        max_percentage_recv: u32,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            duration: Duration::Time(duration),
+            send_recv: (max_percentage_send, max_percentage_recv),
+            mode: FlowMode::default(),
+        }
+    }
+
+    pub fn new_with_mode(
+        name: &str,
+        duration: u64,
+        max_percentage_send: u32,
+        max_percentage_recv: u32,
+        mode: FlowMode,
+    ) -> Self {
+        Self {
+            mode,
+            ..Self::new(name, duration, max_percentage_send, max_percentage_recv)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub gov_module: Addr,
+    pub ibc_module: Addr,
+    /// (channel_id, denom, quota). Use `state::DEFAULT_DENOM` for a quota
+    /// that should apply to every asset on the channel without a more
+    /// specific rule of its own.
+    pub channel_quotas: Vec<(String, String, QuotaMsg)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    SendPacket {
+        channel_id: String,
+        denom: String,
+        channel_value: u128,
+        funds: u128,
+    },
+    RecvPacket {
+        channel_id: String,
+        denom: String,
+        channel_value: u128,
+        funds: u128,
+    },
+    /// Governance-only. Registers the quotas that apply to `denom` on
+    /// `channel_id`, replacing any that were already there.
+    AddChannel {
+        channel_id: String,
+        denom: String,
+        quotas: Vec<QuotaMsg>,
+    },
+    /// Governance-only. Removes the quotas (and accumulated flow) configured
+    /// for `denom` on `channel_id`.
+    RemoveChannel { channel_id: String, denom: String },
+    /// Governance-only. Replaces the quotas configured for `denom` on a live
+    /// channel without resetting its currently accumulated flow.
+    SetQuotas {
+        channel_id: String,
+        denom: String,
+        quotas: Vec<QuotaMsg>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Returns the quotas that would be applied to `denom` on a channel,
+    /// falling back to the channel's `DEFAULT_DENOM` quotas if `denom` has
+    /// none of its own.
+    GetQuotas { channel_id: String, denom: String },
+    /// Returns the live flow for `denom` on a channel, plus the headroom
+    /// still available in each direction given the channel's current value.
+    GetFlow {
+        channel_id: String,
+        denom: String,
+        channel_value: u128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuotasResponse {
+    pub quotas: Vec<Quota>,
+}
+
+/// A single quota's flow, reported independently since quotas on the same
+/// channel/denom (e.g. a tight daily one and a looser weekly one) track
+/// independent windows — summing them together would double count the same
+/// underlying transfers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuotaFlowResponse {
+    pub quota_name: String,
+    pub inflow: u128,
+    pub outflow: u128,
+    pub balance: u128,
+    /// The next time this quota's headroom can change: the period boundary
+    /// for `Fixed`/`Weighted`, or the point at which the oldest bucket still
+    /// counted against the balance ages out for `Rolling`.
+    pub period_end: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlowResponse {
+    pub quotas: Vec<QuotaFlowResponse>,
+    // The channel is throttled by whichever quota has the least headroom; a
+    // channel without any quota configured has unlimited headroom.
+    pub available_send: u128,
+    pub available_recv: u128,
+}
@@ -1,33 +1,84 @@
-use cosmwasm_std::{Addr, Timestamp};
+use cosmwasm_std::{Addr, Timestamp, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::cmp;
 
 use cw_storage_plus::{Item, Map};
 
+use crate::error::ContractError;
 use crate::msg::QuotaMsg;
 
 pub const RESET_TIME_WEEKLY: u64 = 60 * 60 * 24 * 7;
 
+/// Sentinel denom key for quotas that apply to any asset without a more
+/// specific rule of its own. Every such asset on the channel shares the
+/// quotas, and the flow, stored under this key.
+pub const DEFAULT_DENOM: &str = "*";
+
 #[derive(Debug, Clone)]
 pub enum FlowType {
     In,
     Out,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Copy)]
+/// Selects how a `Quota`'s flow is aged out over time.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub enum FlowMode {
+    /// The original behavior: flow accumulates for a fixed period and is
+    /// fully reset to zero once `period_end` passes. Cheap, but lets a
+    /// channel be drained right before the boundary and again right after.
+    #[default]
+    Fixed,
+    /// A sliding window made of fixed-size buckets of `granularity` seconds.
+    /// Before every read or write, buckets more than `duration / granularity`
+    /// buckets behind the current one are dropped, so the reported balance
+    /// reflects the current bucket plus the `duration / granularity` before it.
+    Rolling { granularity: u64 },
+    /// A cheaper approximation of `Rolling`: the previous period's totals
+    /// are weighted by how much of that period is still "inside" the
+    /// window, and added to the current period's totals.
+    Weighted,
+}
+
+/// One rolling-window bucket's accumulated flow.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct Bucket {
+    pub inflow: u128,
+    pub outflow: u128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Flow {
     pub inflow: u128,
     pub outflow: u128,
     pub period_end: Timestamp,
+    /// Start of the current fixed/weighted period. Unused by `FlowMode::Rolling`.
+    pub period_start: Timestamp,
+    /// Totals from the period preceding `period_start`, used by `FlowMode::Weighted`.
+    pub prev_inflow: u128,
+    pub prev_outflow: u128,
+    /// Rolling-window buckets keyed by `bucket_index = now_seconds / granularity`,
+    /// used by `FlowMode::Rolling`. Always empty for the other modes.
+    pub buckets: Vec<(u64, Bucket)>,
 }
 
 impl Flow {
-    pub fn new(inflow: impl Into<u128>, outflow: impl Into<u128>, now: Timestamp) -> Self {
+    /// `duration` is the owning quota's period length, in seconds, and seeds
+    /// `period_end` for `FlowMode::Fixed`/`FlowMode::Weighted`.
+    pub fn new(
+        inflow: impl Into<u128>,
+        outflow: impl Into<u128>,
+        now: Timestamp,
+        duration: u64,
+    ) -> Self {
         Self {
             inflow: inflow.into(),
             outflow: outflow.into(),
-            period_end: now.plus_seconds(RESET_TIME_WEEKLY),
+            period_start: now,
+            period_end: now.plus_seconds(duration.max(1)),
+            prev_inflow: 0,
+            prev_outflow: 0,
+            buckets: vec![],
         }
     }
 
@@ -40,10 +91,13 @@ impl Flow {
     }
 
     // Mutating methods
-    pub fn expire(&mut self, now: Timestamp) {
+    pub fn expire(&mut self, now: Timestamp, duration: u64) {
+        self.prev_inflow = self.inflow;
+        self.prev_outflow = self.outflow;
         self.inflow = 0;
         self.outflow = 0;
-        self.period_end = now.plus_seconds(RESET_TIME_WEEKLY);
+        self.period_start = now;
+        self.period_end = now.plus_seconds(duration.max(1));
     }
 
     pub fn add_flow(&mut self, direction: FlowType, value: u128) {
@@ -52,6 +106,178 @@ impl Flow {
             FlowType::Out => self.outflow = self.outflow.saturating_add(value),
         }
     }
+
+    /// Drops every bucket whose index is more than `duration / granularity`
+    /// behind the index of `now`'s bucket. Must be
+    /// called before any rolling read or write so the ring never grows past
+    /// `duration / granularity + 1` entries.
+    pub fn prune_buckets(&mut self, now: Timestamp, duration: u64, granularity: u64) {
+        let granularity = granularity.max(1);
+        let now_idx = now.seconds() / granularity;
+        let window = duration / granularity;
+        let oldest_live = now_idx.saturating_sub(window);
+        self.buckets.retain(|(idx, _)| *idx >= oldest_live);
+    }
+
+    pub fn add_to_bucket(
+        &mut self,
+        now: Timestamp,
+        granularity: u64,
+        direction: FlowType,
+        value: u128,
+    ) {
+        let idx = now.seconds() / granularity.max(1);
+        match self
+            .buckets
+            .iter_mut()
+            .find(|(bucket_idx, _)| *bucket_idx == idx)
+        {
+            Some((_, bucket)) => match direction {
+                FlowType::In => bucket.inflow = bucket.inflow.saturating_add(value),
+                FlowType::Out => bucket.outflow = bucket.outflow.saturating_add(value),
+            },
+            None => {
+                let mut bucket = Bucket::default();
+                match direction {
+                    FlowType::In => bucket.inflow = value,
+                    FlowType::Out => bucket.outflow = value,
+                }
+                self.buckets.push((idx, bucket));
+            }
+        }
+    }
+
+    /// Sums the live buckets. An empty (fully pruned) ring yields zero.
+    pub fn rolling_flow(&self) -> (u128, u128) {
+        self.buckets
+            .iter()
+            .fold((0_u128, 0_u128), |(inflow, outflow), (_, bucket)| {
+                (
+                    inflow.saturating_add(bucket.inflow),
+                    outflow.saturating_add(bucket.outflow),
+                )
+            })
+    }
+
+    pub fn rolling_balance(&self) -> u128 {
+        let (inflow, outflow) = self.rolling_flow();
+        inflow.abs_diff(outflow)
+    }
+
+    /// Rolls `prev_*` forward once the current period ends, the same way
+    /// `expire` does for `FlowMode::Fixed`, starting a new period of the
+    /// quota's own `duration`.
+    pub fn maybe_roll_period(&mut self, now: Timestamp, duration: u64) {
+        if now >= self.period_end {
+            self.prev_inflow = self.inflow;
+            self.prev_outflow = self.outflow;
+            self.inflow = 0;
+            self.outflow = 0;
+            self.period_start = now;
+            self.period_end = now.plus_seconds(duration.max(1));
+        }
+    }
+
+    /// Weights the previous period's totals by how much of that period is
+    /// still inside the window and adds the current period's totals:
+    /// `effective = prev_total * (remaining / period_len) + current_total`.
+    /// The multiply is carried out the same `checked_multiply_ratio` way
+    /// `Quota::capacity_at` is, since `prev_*` is bounded only by a prior
+    /// period's capacity and can be large enough to overflow a raw `u128`
+    /// multiply by `remaining`.
+    pub fn weighted_flow(
+        &self,
+        now: Timestamp,
+        duration: u64,
+    ) -> Result<(u128, u128), ContractError> {
+        if duration == 0 {
+            return Ok((self.inflow, self.outflow));
+        }
+        let elapsed = now
+            .seconds()
+            .saturating_sub(self.period_start.seconds())
+            .min(duration);
+        let remaining = duration - elapsed;
+
+        let decayed_inflow = Uint128::new(self.prev_inflow)
+            .checked_multiply_ratio(remaining, duration)
+            .map_err(|_| ContractError::Overflow {})?
+            .u128();
+        let decayed_outflow = Uint128::new(self.prev_outflow)
+            .checked_multiply_ratio(remaining, duration)
+            .map_err(|_| ContractError::Overflow {})?
+            .u128();
+
+        Ok((
+            decayed_inflow.saturating_add(self.inflow),
+            decayed_outflow.saturating_add(self.outflow),
+        ))
+    }
+
+    pub fn weighted_balance(&self, now: Timestamp, duration: u64) -> Result<u128, ContractError> {
+        let (inflow, outflow) = self.weighted_flow(now, duration)?;
+        Ok(inflow.abs_diff(outflow))
+    }
+
+    /// Read-only `(inflow, outflow)` for `mode`, without mutating any
+    /// pruning/expiry state. Used by queries, which must not have side
+    /// effects.
+    pub fn effective_flow_for_mode(
+        &self,
+        mode: &FlowMode,
+        now: Timestamp,
+        duration: u64,
+    ) -> Result<(u128, u128), ContractError> {
+        match mode {
+            FlowMode::Fixed => {
+                if self.is_expired(now) {
+                    Ok((0, 0))
+                } else {
+                    Ok((self.inflow, self.outflow))
+                }
+            }
+            FlowMode::Rolling { granularity } => {
+                let mut flow = self.clone();
+                flow.prune_buckets(now, duration, *granularity);
+                Ok(flow.rolling_flow())
+            }
+            FlowMode::Weighted => self.weighted_flow(now, duration),
+        }
+    }
+
+    /// Read-only balance for `mode`, without mutating any pruning/expiry
+    /// state. Used by queries, which must not have side effects.
+    pub fn balance_for_mode(
+        &self,
+        mode: &FlowMode,
+        now: Timestamp,
+        duration: u64,
+    ) -> Result<u128, ContractError> {
+        let (inflow, outflow) = self.effective_flow_for_mode(mode, now, duration)?;
+        Ok(inflow.abs_diff(outflow))
+    }
+
+    /// The next time this quota's headroom can change. For `Fixed`/`Weighted`
+    /// quotas that's the period boundary; `FlowMode::Rolling` has no single
+    /// reset point, since the window slides continuously, so this reports
+    /// when the oldest bucket still counted against the balance ages out —
+    /// the earliest point the balance can start to drop.
+    pub fn reset_at(&self, mode: &FlowMode, now: Timestamp, duration: u64) -> Timestamp {
+        match mode {
+            FlowMode::Fixed | FlowMode::Weighted => self.period_end,
+            FlowMode::Rolling { granularity } => {
+                let granularity = (*granularity).max(1);
+                let mut flow = self.clone();
+                flow.prune_buckets(now, duration, granularity);
+                match flow.buckets.iter().map(|(idx, _)| *idx).min() {
+                    Some(oldest_idx) => Timestamp::from_seconds(
+                        (oldest_idx + duration / granularity + 1) * granularity,
+                    ),
+                    None => now,
+                }
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -60,16 +286,45 @@ pub struct Quota {
     max_percentage_send: u32,
     max_percentage_recv: u32,
     duration: cw_utils::Duration,
+    mode: FlowMode,
 }
 
 impl Quota {
-    /// Calculates the max capacity based on the total value of the channel
-    pub fn capacity_at(&self, total_value: &u128, direction: &FlowType) -> u128 {
+    /// Calculates the max capacity based on the total value of the channel.
+    /// The `total_value * max_percentage` product is carried out in 256-bit
+    /// precision (the same pattern `Uint128::checked_multiply_ratio` uses)
+    /// so a high-TVL channel can't wrap the multiplication before the divide.
+    pub fn capacity_at(
+        &self,
+        total_value: &u128,
+        direction: &FlowType,
+    ) -> Result<u128, ContractError> {
         let max_percentage = match direction {
             FlowType::In => self.max_percentage_recv,
             FlowType::Out => self.max_percentage_send,
         };
-        total_value * (max_percentage as u128) / 100_u128
+        Uint128::new(*total_value)
+            .checked_multiply_ratio(max_percentage as u128, 100_u128)
+            .map(|capacity| capacity.u128())
+            .map_err(|_| ContractError::Overflow {})
+    }
+
+    pub fn mode(&self) -> &FlowMode {
+        &self.mode
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The quota's `duration`, in seconds. Block-height durations aren't
+    /// meaningful for time-based flow accounting, so they fall back to the
+    /// weekly reset.
+    pub fn duration_secs(&self) -> u64 {
+        match self.duration {
+            cw_utils::Duration::Time(seconds) => seconds,
+            cw_utils::Duration::Height(_) => RESET_TIME_WEEKLY,
+        }
     }
 }
 
@@ -84,6 +339,7 @@ impl From<QuotaMsg> for Quota {
             max_percentage_send: send_recv.0,
             max_percentage_recv: send_recv.1,
             duration: msg.duration,
+            mode: msg.mode,
         }
     }
 }
@@ -98,5 +354,12 @@ pub const IBCMODULE: Item<Addr> = Item::new("ibc_module");
 //
 // It is the responsibility of the go module to pass the appropriate channel
 // when sending the messages
-pub const QUOTAS: Map<String, Vec<Quota>> = Map::new("quotas");
-pub const FLOW: Map<String, Flow> = Map::new("flow");
+/// Keyed by `(channel_id, denom)` so a surge in one token can't exhaust the
+/// allowance shared by every other asset on the channel. Denoms without a
+/// rule of their own fall back to whatever is stored under `DEFAULT_DENOM`.
+pub const QUOTAS: Map<(String, String), Vec<Quota>> = Map::new("quotas");
+/// Keyed by `(channel_id, denom, quota_name)` so quotas with different
+/// `duration`s on the same channel (e.g. a tight daily limit and a looser
+/// weekly one), and quotas scoped to different denoms, each track their own
+/// flow instead of sharing one period or one bucket.
+pub const FLOW: Map<(String, String, String), Flow> = Map::new("flow");